@@ -0,0 +1,24 @@
+use core::Workspaces;
+use window_manager::ManageRule;
+use window_system::Window;
+
+/// User-facing configuration for the window manager, built up by the
+/// user's config file and handed to `WindowManager` and its methods.
+pub struct Config {
+    pub tags: Vec<String>,
+    pub border_width: u32,
+    pub border_color: u32,
+    pub focus_border_color: u32,
+    /// Called with the workspaces, right after a newly managed window was
+    /// inserted, and the window itself, to let the user apply an imperative
+    /// manage hook (e.g. always float a certain window) ahead of
+    /// `manage_rules`.
+    pub manage_hook: Box<Fn<(Workspaces, Window), Workspaces> + 'static>,
+    /// Declarative rules evaluated, in order, against newly managed
+    /// windows, so users can pin applications to a workspace, screen or
+    /// the float layer without hand-writing an imperative manage hook.
+    pub manage_rules: Vec<ManageRule>,
+    /// If true, entering a window's frame with the pointer focuses it,
+    /// via `WindowManager::enter_notify`.
+    pub focus_follows_mouse: bool
+}
@@ -0,0 +1,42 @@
+use core::Stack;
+use window_manager::LayoutMessage;
+use window_system::Rectangle;
+use window_system::Window;
+
+/// A tiling algorithm, responsible for arranging the windows of a single
+/// workspace's stack within a given rectangle. Layouts are cloned around
+/// as boxed trait objects (via `copy`) so a workspace's layout instance
+/// can carry its own mutable state (master ratio, master count, ...)
+/// independently of any other workspace running the "same" layout.
+pub trait Layout {
+    /// Arrange the windows in `stack` within `screen`, returning each
+    /// window's on-screen geometry.
+    fn apply_layout(&self, screen: Rectangle, stack: &Option<Stack<Window>>) -> Vec<(Window, Rectangle)>;
+
+    /// Handle a `LayoutMessage`, returning a new boxed layout with the
+    /// message applied, or `None` if this layout doesn't know how to
+    /// handle it, leaving the current layout unchanged.
+    fn handle_message(&self, message: LayoutMessage) -> Option<Box<Layout + Send>> {
+        None
+    }
+
+    /// A short, stable name identifying this layout.
+    fn description(&self) -> String;
+
+    /// Clone this layout into a new boxed trait object, since `Box<Layout>`
+    /// can't derive `Clone` directly.
+    fn copy(&self) -> Box<Layout + Send>;
+}
+
+/// Entry point for constructing a fresh, default-state layout instance by
+/// name, e.g. for a workspace that has no live layout instance of its
+/// own yet.
+pub struct LayoutManager;
+
+impl LayoutManager {
+    /// No concrete layouts are registered in this module: this panics
+    /// via `unimplemented!()` for every name, and returns no layout.
+    pub fn get_layout(name: String) -> Box<Layout + Send> {
+        unimplemented!()
+    }
+}
@@ -2,16 +2,144 @@ use core::Screen;
 use core::Workspace;
 use core::Workspaces;
 use config::Config;
+use layout::Layout;
 use layout::LayoutManager;
+use std::collections::HashMap;
+use window_system::RationalRect;
 use window_system::Rectangle;
 use window_system::Window;
 use window_system::WindowSystem;
 
 pub type ScreenDetail = Rectangle;
 
+/// A message sent to the current layout, letting it mutate its own state
+/// in place of swapping in an entirely new layout. Mirrors xmonad's
+/// `sendMessage`.
 #[deriving(Clone)]
+pub enum LayoutMessage {
+    /// Shrink the master area.
+    Shrink,
+    /// Expand the master area.
+    Expand,
+    /// Set the number of windows kept in the master area, relative to
+    /// the current count.
+    IncMasterN(i32),
+    /// Advance to the next layout in the layout's own rotation.
+    NextLayout,
+    /// A layout-specific message not covered by the cases above.
+    Custom(String)
+}
+
+/// Tests a window property fetched through the `WindowSystem`, used to
+/// decide whether a `ManageRule` applies to a newly managed window.
+#[deriving(Clone)]
+pub enum WindowMatcher {
+    /// Match against the window's WM_CLASS.
+    ClassName(String),
+    /// Match against the window's WM_NAME.
+    Title(String),
+    /// Match against the window's role property.
+    Role(String)
+}
+
+impl WindowMatcher {
+    fn matches(&self, window_system: &WindowSystem, window: Window) -> bool {
+        match *self {
+            WindowMatcher::ClassName(ref class) => window_system.get_window_class(window) == *class,
+            WindowMatcher::Title(ref title)     => window_system.get_window_name(window) == *title,
+            WindowMatcher::Role(ref role)       => window_system.get_window_role(window) == *role
+        }
+    }
+}
+
+/// What to do with a window that matches a `ManageRule`.
+#[deriving(Clone)]
+pub enum ManageAction {
+    /// Shift the window to the workspace with the given index.
+    MoveToWorkspace(u32),
+    /// Float the window at the given position/size, as a fraction of
+    /// its screen's rectangle.
+    Float(RationalRect),
+    /// Shift the window to the screen with the given index.
+    MoveToScreen(u32),
+    /// Leave the window alone.
+    Ignore
+}
+
+/// A declarative rule evaluated against newly managed windows, so users
+/// can pin applications to a workspace, screen or the float layer
+/// without hand-writing an imperative manage hook.
+#[deriving(Clone)]
+pub struct ManageRule {
+    pub matcher: WindowMatcher,
+    pub action: ManageAction,
+    /// If true, the rule only ever fires the first time the window is
+    /// managed, so it won't keep pulling the window back after the user
+    /// has moved it by hand.
+    pub initial_only: bool
+}
+
+/// Screen space reserved on each edge by a dock/panel window advertising
+/// `_NET_WM_STRUT`/`_NET_WM_STRUT_PARTIAL`, in pixels.
+#[deriving(Clone)]
+pub struct Strut {
+    pub left: u32,
+    pub right: u32,
+    pub top: u32,
+    pub bottom: u32
+}
+
+impl Strut {
+    pub fn empty() -> Strut {
+        Strut { left: 0, right: 0, top: 0, bottom: 0 }
+    }
+
+    fn add(&self, other: &Strut) -> Strut {
+        Strut {
+            left: self.left + other.left,
+            right: self.right + other.right,
+            top: self.top + other.top,
+            bottom: self.bottom + other.bottom
+        }
+    }
+}
+
 pub struct WindowManager {
-    pub workspaces: Workspaces
+    pub workspaces: Workspaces,
+    /// Floating windows, keyed by window and storing their position and
+    /// size as fractions (0.0-1.0) of their screen's rectangle, so they
+    /// can be rescaled whenever the screen arrangement changes.
+    pub floating: HashMap<Window, RationalRect>,
+    /// Dock/panel windows, keyed by window and storing the id of the
+    /// screen they're on plus the screen space they reserve for
+    /// themselves there, queried from `_NET_WM_STRUT`/
+    /// `_NET_WM_STRUT_PARTIAL`. These windows are mapped but never
+    /// resized or tiled.
+    pub struts: HashMap<Window, (u32, Strut)>,
+    /// Live layout instances, keyed by workspace id. `send_layout_message`
+    /// mutates a workspace's layout in place here; `reapply_layout` reads
+    /// from this cache instead of rebuilding a default layout from
+    /// `workspace.layout`'s name, so master-ratio/master-count state
+    /// survives view switches instead of being discarded on every re-tile.
+    pub layouts: HashMap<u32, Box<Layout + Send>>,
+    /// The server request serial as of the last `reapply_layout`. Windows
+    /// moving under a stationary pointer can make the X server deliver a
+    /// synthetic EnterNotify; `enter_notify` ignores any event at or
+    /// before this serial rather than treating it as genuine pointer
+    /// motion, so re-tiling doesn't fight the user's actual pointer.
+    pub last_retile_serial: u64
+}
+
+impl Clone for WindowManager {
+    fn clone(&self) -> WindowManager {
+        WindowManager {
+            workspaces: self.workspaces.clone(),
+            floating: self.floating.clone(),
+            struts: self.struts.clone(),
+            layouts: self.layouts.iter().map(|(id, l)| (*id, l.copy())).collect(),
+            last_retile_serial: self.last_retile_serial
+        }
+    }
 }
 
 impl WindowManager {
@@ -20,7 +148,11 @@ impl WindowManager {
         WindowManager {
             workspaces: Workspaces::new(String::from_str("Tall"),
                                         config.tags.clone(),
-                                        window_system.get_screen_infos())
+                                        window_system.get_screen_infos()),
+            floating: HashMap::new(),
+            struts: HashMap::new(),
+            layouts: HashMap::new(),
+            last_retile_serial: 0
         }
     }
 
@@ -79,12 +211,27 @@ impl WindowManager {
     pub fn reapply_layout(&self, window_system: &WindowSystem, config: &Config) -> WindowManager {
         for screen in self.workspaces.screens().iter() {
             let workspace = &screen.workspace;
-            let layout = LayoutManager::get_layout(workspace.layout.clone());
+            let layout = self.layout_for(workspace.id, workspace.layout.clone());
 
-            let Rectangle(x, y, w, h) = screen.screen_detail;
-            let screen_space = Rectangle(x, y + 20, w, h - 20);
+            let screen_space = self.screen_space(screen.screen_detail, screen.screen_id);
 
-            let window_layout = layout.apply_layout(screen_space, &workspace.stack);
+            // Floating and strut windows never go through the tiling
+            // layout at all, so it divides the tileable space as if they
+            // weren't part of the stack, rather than allocating them a
+            // slot and then discarding it.
+            let tiled_stack = workspace.stack.clone()
+                .and_then(|s| s.filter(|win: &Window| !self.floating.contains_key(win) && !self.struts.contains_key(win)));
+
+            // Windows that are floating are positioned by scaling their
+            // stored RationalRect against this screen, rather than by the
+            // tiling layout, so they follow their screen across rescreens.
+            let window_layout : Vec<(Window, Rectangle)> = layout.apply_layout(screen_space, &tiled_stack);
+
+            let floating_layout : Vec<(Window, Rectangle)> = workspace.stack.clone()
+                .map_or(Vec::new(), |s| s.integrate())
+                .iter()
+                .filter_map(|&win| self.floating.find(&win).map(|&rect| (win, scale_rational_rect(screen_space, rect))))
+                .collect();
 
             debug!("reapplying layout to {} screen", screen.screen_detail);
 
@@ -101,8 +248,10 @@ impl WindowManager {
                 }
             }
 
-            // Then, show, place and resize all now visible windows.
-            for &(win, Rectangle(x, y, w, h)) in window_layout.iter() {
+            // Then, show, place and resize all now visible windows. Tiled
+            // windows are shown first and floating windows last, so floats
+            // always end up stacked above the tiled layer.
+            for &(win, Rectangle(x, y, w, h)) in window_layout.iter().chain(floating_layout.iter()) {
                 debug!("Show window {} ({})", win, window_system.get_window_name(win));
                 window_system.show_window(win);
                 window_system.resize_window(win, w - config.border_width * 2, h - config.border_width * 2);
@@ -110,6 +259,17 @@ impl WindowManager {
                 window_system.set_window_border_width(win, config.border_width);
                 window_system.set_window_border_color(win, config.border_color);
             }
+
+            // Dock/panel windows are mapped but left exactly where they
+            // are; they reserved their own screen space above, and are
+            // never moved or resized by a layout. Only show the ones
+            // that belong to this screen, so each dock is mapped once,
+            // on its own screen's pass, rather than once per screen.
+            let screen_struts = self.struts.iter()
+                .filter_map(|(&win, &(id, _))| if id == screen.screen_id { Some(win) } else { None });
+            for win in screen_struts {
+                window_system.show_window(win);
+            }
         }
 
         match self.workspaces.peek() {
@@ -120,7 +280,46 @@ impl WindowManager {
         // Force a redraw on all windows.
         window_system.flush();
 
-        self.clone()
+        // Record the server's request serial now that every window has
+        // been (re)placed, so `enter_notify` can recognize and ignore the
+        // EnterNotify this re-tile may have just generated under a
+        // stationary pointer, without swallowing a later genuine one.
+        let mut result = self.clone();
+        result.last_retile_serial = window_system.get_event_serial();
+        result
+    }
+
+    /// Send a message to the layout of the currently focused workspace,
+    /// letting it mutate its own state (e.g. grow the master area or
+    /// change the master window count). If the layout handles the
+    /// message, the resulting layout *instance* is persisted into
+    /// `self.layouts` and the layout is reapplied; otherwise nothing
+    /// happens. The instance, not just its description, is what's kept
+    /// around, so the mutated state survives the next view switch.
+    pub fn send_layout_message(&self, window_system: &WindowSystem, config: &Config,
+                               message: LayoutMessage) -> WindowManager {
+        let workspace = &self.workspaces.current.workspace;
+        let layout = self.layout_for(workspace.id, workspace.layout.clone());
+
+        match layout.handle_message(message) {
+            Some(new_layout) => {
+                let mut w = self.clone();
+                w.layouts.insert(workspace.id, new_layout);
+                w.reapply_layout(window_system, config)
+            }
+            None => self.clone()
+        }
+    }
+
+    /// Look up the live layout instance for a workspace, falling back to
+    /// a freshly constructed default layout (by name) if none has been
+    /// created yet, e.g. because the workspace has never received a
+    /// `LayoutMessage`.
+    fn layout_for(&self, workspace_id: u32, default_name: String) -> Box<Layout + Send> {
+        match self.layouts.get(&workspace_id) {
+            Some(layout) => layout.copy(),
+            None         => LayoutManager::get_layout(default_name)
+        }
     }
 
     pub fn unfocus_windows(&self, window_system: &WindowSystem, config: &Config) {
@@ -133,9 +332,138 @@ impl WindowManager {
     /// when the WM started.
     pub fn manage(&self, window_system: &WindowSystem, window: Window, config: &Config) -> WindowManager {
         debug!("managing window \"{}\" ({})", window_system.get_window_name(window), window);
-        // TODO: manage floating windows
-        // and ensure that they stay within screen boundaries
-        self.windows(window_system, config, |x| config.manage_hook.call((x.insert_up(window), window)))
+        let managed = self.windows(window_system, config, |x| config.manage_hook.call((x.insert_up(window), window)));
+        managed.apply_manage_rules(window_system, config, window)
+    }
+
+    /// Evaluate `config.manage_rules` against a newly managed window and
+    /// apply the first match, in order, regardless of `initial_only`:
+    /// this is the window's first appearance, so even a rule that's
+    /// supposed to fire only once is still due to fire.
+    fn apply_manage_rules(&self, window_system: &WindowSystem, config: &Config, window: Window) -> WindowManager {
+        self.apply_first_matching_rule(window_system, config, window, config.manage_rules.iter())
+    }
+
+    /// Re-evaluate `config.manage_rules` against a window whose
+    /// properties changed after it was already managed (e.g. its
+    /// WM_CLASS or WM_NAME was updated in place), applying only rules
+    /// with `initial_only == false`. Call this from the property-change
+    /// event path; rules with `initial_only == true` already fired from
+    /// `manage` and must not keep pulling the window back after the user
+    /// has since moved it by hand.
+    pub fn apply_manage_rules_on_property_change(&self, window_system: &WindowSystem, config: &Config, window: Window) -> WindowManager {
+        if !self.is_window_managed(window) {
+            return self.clone();
+        }
+
+        self.apply_first_matching_rule(window_system, config, window,
+                                       config.manage_rules.iter().filter(|rule| !rule.initial_only))
+    }
+
+    /// Shared by `apply_manage_rules` and `apply_manage_rules_on_property_change`:
+    /// apply the action of the first rule in `rules` whose matcher
+    /// matches `window`. Every action targets `window` specifically,
+    /// never whatever window happens to be focused right now, since on
+    /// the property-change path the matched window is rarely the
+    /// focused one.
+    fn apply_first_matching_rule<'a, I: Iterator<&'a ManageRule>>(&self, window_system: &WindowSystem,
+                                                                  config: &Config, window: Window, mut rules: I) -> WindowManager {
+        for rule in rules {
+            if rule.matcher.matches(window_system, window) {
+                return match rule.action {
+                    ManageAction::MoveToWorkspace(index) => self.windows(window_system, config, |w| w.shift_win(index, window)),
+                    ManageAction::MoveToScreen(index)    => {
+                        match self.workspaces.screens().iter().find(|s| s.screen_id == index) {
+                            Some(screen) => self.windows(window_system, config, |w| w.shift_win(screen.workspace.id, window)),
+                            None         => self.clone()
+                        }
+                    }
+                    ManageAction::Float(rect) => self.float(window_system, config, window, rect),
+                    ManageAction::Ignore      => self.clone()
+                };
+            }
+        }
+
+        self.clone()
+    }
+
+    /// Float the given window at the given position/size, expressed as a
+    /// fraction of its screen's rectangle, and re-tile so it takes effect.
+    pub fn float(&self, window_system: &WindowSystem, config: &Config,
+                 window: Window, rect: RationalRect) -> WindowManager {
+        let mut w = self.clone();
+        w.floating.insert(window, rect);
+        w.reapply_layout(window_system, config)
+    }
+
+    /// Remove the given window from the float layer so it rejoins the
+    /// tiled stack, and re-tile so it takes effect.
+    pub fn clear_floating(&self, window_system: &WindowSystem, config: &Config,
+                          window: Window) -> WindowManager {
+        let mut w = self.clone();
+        w.floating.remove(&window);
+        w.reapply_layout(window_system, config)
+    }
+
+    /// Handle a mod+button1 drag: move the window to follow the pointer,
+    /// floating it first if it wasn't already.
+    pub fn mouse_move_window(&self, window_system: &WindowSystem, config: &Config,
+                             window: Window, x: i32, y: i32) -> WindowManager {
+        let screen = self.workspaces.find_screen(window);
+        let Rectangle(sx, sy, sw, sh) = self.screen_space(screen.screen_detail, screen.screen_id);
+        let (w, h) = match self.floating.find(&window) {
+            Some(&RationalRect(_, _, w, h)) => (w, h),
+            None                            => (0.25f32, 0.25f32)
+        };
+
+        window_system.move_window(window, x, y);
+        self.float(window_system, config, window,
+                   RationalRect((x - sx) as f32 / sw as f32, (y - sy) as f32 / sh as f32, w, h))
+    }
+
+    /// Handle a mod+button3 drag: resize the window by dragging its
+    /// bottom-right corner to the pointer, floating it first if it
+    /// wasn't already.
+    pub fn mouse_resize_window(&self, window_system: &WindowSystem, config: &Config,
+                               window: Window, x: i32, y: i32) -> WindowManager {
+        let screen = self.workspaces.find_screen(window);
+        let Rectangle(sx, sy, sw, sh) = self.screen_space(screen.screen_detail, screen.screen_id);
+        let (fx, fy) = match self.floating.find(&window) {
+            Some(&RationalRect(x, y, _, _)) => (x, y),
+            None                            => (0.0f32, 0.0f32)
+        };
+
+        let w = ((x - sx) as f32 / sw as f32 - fx).max(0.05f32);
+        let h = ((y - sy) as f32 / sh as f32 - fy).max(0.05f32);
+
+        window_system.resize_window(window, (sw as f32 * w) as u32, (sh as f32 * h) as u32);
+        self.float(window_system, config, window, RationalRect(fx, fy, w, h))
+    }
+
+    /// Record or refresh the strut reserved by a dock/panel window on the
+    /// given screen, and re-tile so the new reservation takes effect
+    /// immediately. Call this whenever such a window is mapped or
+    /// changes its strut property, passing the id of the screen it was
+    /// mapped/configured on so its reservation is scoped to that screen.
+    pub fn update_strut(&self, window_system: &WindowSystem, config: &Config, window: Window, screen_id: u32) -> WindowManager {
+        let mut w = self.clone();
+
+        match window_system.get_window_strut(window) {
+            Some((left, right, top, bottom)) => {
+                w.struts.insert(window, (screen_id, Strut { left: left, right: right, top: top, bottom: bottom }));
+            }
+            None => { w.struts.remove(&window); }
+        }
+
+        w.reapply_layout(window_system, config)
+    }
+
+    /// Forget a dock/panel window's strut, e.g. because it was unmapped,
+    /// and re-tile so its reserved space is returned to the layout.
+    pub fn remove_strut(&self, window_system: &WindowSystem, config: &Config, window: Window) -> WindowManager {
+        let mut w = self.clone();
+        w.struts.remove(&window);
+        w.reapply_layout(window_system, config)
     }
 
     /// Unmanage a window. This happens when a window is closed.
@@ -160,6 +488,26 @@ impl WindowManager {
         }
     }
 
+    /// Handle the pointer entering a managed window (an EnterNotify event
+    /// delivered from the window system, along with its server serial).
+    /// When `config.focus_follows_mouse` is set, the entered window isn't
+    /// already focused, and the event's serial is after `last_retile_serial`,
+    /// this focuses it through the same `focus` path as an explicit focus
+    /// request, so the border-color bookkeeping in `windows`/`focus` runs
+    /// as usual. Events at or before `last_retile_serial` are the
+    /// EnterNotify a window move can generate under a stationary pointer
+    /// during `reapply_layout`, not genuine pointer motion, and are
+    /// ignored so re-tiling doesn't fight the user's actual pointer.
+    pub fn enter_notify(&self, window_system: &WindowSystem, config: &Config, window: Window, serial: u64) -> WindowManager {
+        if config.focus_follows_mouse
+            && serial > self.last_retile_serial
+            && self.workspaces.peek() != Some(window) {
+            self.focus(window, window_system, config)
+        } else {
+            self.clone()
+        }
+    }
+
     pub fn focus_down(&self) -> WindowManager {
         let mut w = self.clone();
         w.workspaces = self.workspaces.focus_down();
@@ -223,4 +571,35 @@ impl WindowManager {
 
         result
     }
+
+    /// The tileable rectangle of a screen after subtracting the strut
+    /// space reserved on that screen specifically, shared by
+    /// `reapply_layout` (to lay out tiled and floating windows) and by
+    /// `mouse_move_window`/`mouse_resize_window` (to store and scale a
+    /// dragged float's `RationalRect` against the same frame it will
+    /// later be rendered against). Struts reserved on other screens are
+    /// not subtracted, so a dock on one monitor doesn't shrink another.
+    fn screen_space(&self, screen_detail: Rectangle, screen_id: u32) -> Rectangle {
+        let reserved = self.struts.values()
+            .filter(|&&(id, _)| id == screen_id)
+            .fold(Strut::empty(), |acc, &(_, ref s)| acc.add(s));
+        let Rectangle(x, y, w, h) = screen_detail;
+        // A strut larger than the screen (or several summing past it)
+        // must not underflow the usable width/height; clamp to zero
+        // instead of wrapping.
+        let usable_w = w.saturating_sub(reserved.left).saturating_sub(reserved.right);
+        let usable_h = h.saturating_sub(reserved.top).saturating_sub(reserved.bottom);
+        Rectangle(x + reserved.left as i32, y + reserved.top as i32, usable_w, usable_h)
+    }
+}
+
+/// Scale a fractional `RationalRect` against an absolute screen rectangle
+/// to get a floating window's actual on-screen geometry.
+fn scale_rational_rect(screen: Rectangle, rect: RationalRect) -> Rectangle {
+    let Rectangle(sx, sy, sw, sh) = screen;
+    let RationalRect(x, y, w, h) = rect;
+    Rectangle(sx + (sw as f32 * x) as i32,
+              sy + (sh as f32 * y) as i32,
+              (sw as f32 * w) as u32,
+              (sh as f32 * h) as u32)
 }
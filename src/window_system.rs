@@ -0,0 +1,47 @@
+/// An opaque handle to an X window, as returned by the underlying window
+/// system implementation.
+pub type Window = u64;
+
+/// An absolute, pixel-valued rectangle: top-left x/y plus width/height.
+#[deriving(Clone, PartialEq, Show)]
+pub struct Rectangle(pub i32, pub i32, pub u32, pub u32);
+
+/// A rectangle expressed as fractions (0.0-1.0) of some other rectangle,
+/// typically a screen. Used for floating window geometry, so a float
+/// keeps its relative position and size when the screen it's on is
+/// resized or rearranged.
+#[deriving(Clone, PartialEq, Show)]
+pub struct RationalRect(pub f32, pub f32, pub f32, pub f32);
+
+/// The interface the window manager drives to talk to the underlying
+/// display server. Implemented against Xlib elsewhere; kept as a trait
+/// here so the core window manager logic stays testable without a real
+/// display connection.
+pub trait WindowSystem {
+    fn get_screen_infos(&self) -> Vec<Rectangle>;
+    fn get_root(&self) -> Window;
+    fn hide_window(&self, window: Window);
+    fn show_window(&self, window: Window);
+    fn resize_window(&self, window: Window, width: u32, height: u32);
+    fn move_window(&self, window: Window, x: i32, y: i32);
+    fn focus_window(&self, window: Window);
+    fn set_window_border_width(&self, window: Window, width: u32);
+    fn set_window_border_color(&self, window: Window, color: u32);
+    fn get_window_name(&self, window: Window) -> String;
+    fn flush(&self);
+    /// The X server's current request serial, e.g. `XNextRequest`. Used
+    /// to tell a real EnterNotify apart from one generated by windows
+    /// moving under a stationary pointer during `reapply_layout`.
+    fn get_event_serial(&self) -> u64;
+
+    /// The window's WM_CLASS, used by `WindowMatcher::ClassName` to
+    /// match `ManageRule`s against managed windows.
+    fn get_window_class(&self, window: Window) -> String;
+    /// The window's WM_WINDOW_ROLE property, used by `WindowMatcher::Role`.
+    fn get_window_role(&self, window: Window) -> String;
+    /// The screen space a dock/panel window reserves for itself via
+    /// `_NET_WM_STRUT`/`_NET_WM_STRUT_PARTIAL`, as `(left, right, top,
+    /// bottom)` pixel margins, or `None` if the window doesn't advertise
+    /// a strut.
+    fn get_window_strut(&self, window: Window) -> Option<(u32, u32, u32, u32)>;
+}